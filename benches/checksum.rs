@@ -0,0 +1,31 @@
+extern crate reliable_udp;
+use reliable_udp::packet::{Header, PType, MAX_PAYLOAD_SIZE};
+use std::hint::black_box;
+use std::time::Instant;
+
+// cargo bench --bench checksum [--features simd]
+fn main() {
+    let data = vec![0xa5u8; MAX_PAYLOAD_SIZE];
+    let header_checksum = Header::calculate_header_checksum(1, 2, PType::Psh);
+    let iterations = 2000;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(Header::calculate_checksum(
+            1,
+            2,
+            PType::Psh,
+            header_checksum,
+            Some(black_box(&data)),
+        ));
+    }
+    let elapsed = start.elapsed();
+
+    let bytes = (iterations * data.len()) as f64;
+    println!(
+        "checksum over {} byte payloads (simd: {}): {:.2} GB/s",
+        data.len(),
+        cfg!(feature = "simd"),
+        bytes / elapsed.as_secs_f64() / 1e9
+    );
+}
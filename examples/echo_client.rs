@@ -1,5 +1,5 @@
 extern crate reliable_udp;
-use rand::Rng;
+use rand::RngExt;
 use std::str;
 
 use reliable_udp::manager;
@@ -11,47 +11,31 @@ use tokio::net::UdpSocket;
 async fn main() -> Result<(), Box<dyn Error>> {
     let server_address = "127.0.0.1:5050";
 
-    let mut rng = rand::thread_rng();
+    let mut rng = rand::rng();
 
     let socket = UdpSocket::bind("0.0.0.0:4040").await?;
 
     let mut buffer: [u8; 1024] = [0; 1024];
 
     // send Syn packet
-    let seq = rng.gen();
+    let seq = rng.random();
     let mut connection = manager::Connection {
-        seq: seq,
-        ack: 0,
-        previous_seq: seq,
-        is_open: false,
+        seq,
         last_response: 5,
+        ..Default::default()
     };
 
-    let header_checksum =
-        packet::Header::calculate_header_checksum(connection.seq, 0, packet::PType::Syn);
-    let checksum = packet::Header::calculate_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::Syn,
-        header_checksum,
-        None,
-    );
-    let packet_header = packet::Header {
-        seq: connection.seq,
-        ack: connection.ack,
-        ptype: packet::PType::Syn,
-        header_checksum,
-        checksum,
-    };
-    let packet = packet::packet_to_binary(packet_header, None);
+    let packet_header =
+        packet::Header::from_connection_state(&connection, packet::PType::Syn, None);
+    let packet = packet::packet_to_binary(packet_header, None)?;
     socket.send_to(&packet, server_address).await?;
 
-    connection.seq += 1;
+    connection.advance_seq(1)?;
 
     // receive SynAck packet
-    let (_, addr) = socket.recv_from(&mut buffer).await?;
-    let packet_header = reliable_udp::packet::Header::parse(&buffer[..packet::HEADER_SIZE])?;
-    if packet_header.ptype != packet::PType::SynAck {
+    let (size, _) = socket.recv_from(&mut buffer).await?;
+    let packet_header = connection.parse_packet(&buffer[..size])?;
+    if packet_header.ptype() != packet::PType::SynAck {
         println!("Not a SynAck packet");
         return Ok(());
     }
@@ -59,71 +43,38 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("Bad checksum");
         return Ok(());
     }
-    if packet_header.ack != connection.seq {
+    if packet_header.ack() != connection.seq {
         println!("Packet needs to be resent");
         return Ok(());
     }
 
-    connection.ack = packet_header.seq + 1;
+    connection.ack = packet_header.seq().wrapping_add(1);
     connection.is_open = true;
 
     // send Ack packet
-    let header_checksum = packet::Header::calculate_header_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::Ack,
-    );
-    let checksum = packet::Header::calculate_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::Ack,
-        header_checksum,
-        None,
-    );
-    let packet_header = packet::Header {
-        seq: connection.seq,
-        ack: connection.ack,
-        ptype: packet::PType::Ack,
-        header_checksum,
-        checksum,
-    };
-    let packet = packet::packet_to_binary(packet_header, None);
+    let packet_header =
+        packet::Header::from_connection_state(&connection, packet::PType::Ack, None);
+    let packet = packet::packet_to_binary(packet_header, None)?;
     socket.send_to(&packet, server_address).await?;
 
     println!("Connection established");
 
     // send Psh packet with data
-    let data = Some(b"Echo me!".as_slice());
-    let header_checksum = packet::Header::calculate_header_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::Psh,
-    );
-    let checksum = packet::Header::calculate_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::Psh,
-        header_checksum,
-        data,
-    );
-    let packet_header = packet::Header {
-        seq: connection.seq,
-        ack: connection.ack,
-        ptype: packet::PType::Psh,
-        header_checksum,
-        checksum,
-    };
-    let packet = packet::packet_to_binary(packet_header, data);
+    let message = b"Echo me!".as_slice();
+    let data = Some(message);
+    let packet_header =
+        packet::Header::from_connection_state(&connection, packet::PType::Psh, data);
+    let packet = packet::packet_to_binary(packet_header, data)?;
     socket.send_to(&packet, server_address).await?;
 
-    connection.seq += unsafe { data.unwrap_unchecked().len() as u32 };
+    connection.advance_seq(message.len())?;
     println!("Message sent");
 
     // receive Psh packet with data
-    let (size, addr) = socket.recv_from(&mut buffer).await?;
-    let packet_header = reliable_udp::packet::Header::parse(&buffer[..packet::HEADER_SIZE])?;
-    let packet_payload = &buffer[packet::HEADER_SIZE..size];
-    if packet_header.ptype != packet::PType::Psh {
+    let (size, _) = socket.recv_from(&mut buffer).await?;
+    let packet_header = connection.parse_packet(&buffer[..size])?;
+    let packet_payload = &buffer[packet_header.payload_offset()..size];
+    if packet_header.ptype() != packet::PType::Psh {
         println!("Not a SynAck packet");
         return Ok(());
     }
@@ -133,7 +84,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("Bad checksum");
         return Ok(());
     }
-    if packet_header.ack != connection.seq {
+    if packet_header.ack() != connection.seq {
         println!("Packet needs to be resent");
         return Ok(());
     }
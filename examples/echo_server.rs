@@ -1,5 +1,5 @@
 extern crate reliable_udp;
-use rand::Rng;
+use rand::RngExt;
 use std::str;
 
 use reliable_udp::manager;
@@ -9,16 +9,17 @@ use tokio::net::UdpSocket;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let mut rng = rand::thread_rng();
+    let mut rng = rand::rng();
 
     let socket = UdpSocket::bind("0.0.0.0:5050").await?;
 
     let mut buffer: [u8; 1024] = [0; 1024];
 
     // receive Syn packet
-    let (_, addr) = socket.recv_from(&mut buffer).await?;
-    let packet_header = reliable_udp::packet::Header::parse(&buffer[..packet::HEADER_SIZE])?;
-    if packet_header.ptype != packet::PType::Syn {
+    let (size, addr) = socket.recv_from(&mut buffer).await?;
+    let packet_header =
+        packet::Header::parse_with_limit(&buffer[..size], packet::DEFAULT_MAX_DATAGRAM_SIZE)?;
+    if packet_header.ptype() != packet::PType::Syn {
         println!("Not a Syn packet");
         return Ok(());
     }
@@ -27,45 +28,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
-    let seq = rng.gen();
+    let seq = rng.random();
     let mut connection = manager::Connection {
-        seq: seq,
-        ack: packet_header.seq + 1,
-        previous_seq: seq,
-        is_open: false,
+        seq,
+        ack: packet_header.seq().wrapping_add(1),
         last_response: 5,
+        ..Default::default()
     };
 
     // send SynAck packet
 
-    let header_checksum = packet::Header::calculate_header_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::SynAck,
-    );
-    let checksum = packet::Header::calculate_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::SynAck,
-        header_checksum,
-        None,
-    );
-    let packet_header = packet::Header {
-        seq: connection.seq,
-        ack: connection.ack,
-        ptype: packet::PType::SynAck,
-        header_checksum,
-        checksum,
-    };
-    let packet = packet::packet_to_binary(packet_header, None);
+    let packet_header =
+        packet::Header::from_connection_state(&connection, packet::PType::SynAck, None);
+    let packet = packet::packet_to_binary(packet_header, None)?;
     socket.send_to(&packet, addr).await?;
 
-    connection.seq += 1;
+    connection.advance_seq(1)?;
 
     // receive Ack packet
-    let (_, addr) = socket.recv_from(&mut buffer).await?;
-    let packet_header = reliable_udp::packet::Header::parse(&buffer[..packet::HEADER_SIZE])?;
-    if packet_header.ptype != packet::PType::Ack {
+    let (size, _) = socket.recv_from(&mut buffer).await?;
+    let packet_header = connection.parse_packet(&buffer[..size])?;
+    if packet_header.ptype() != packet::PType::Ack {
         println!("Not a Syn packet");
         return Ok(());
     }
@@ -73,7 +56,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("Bad checksum");
         return Ok(());
     }
-    if packet_header.ack != connection.seq || packet_header.seq != connection.ack {
+    if packet_header.ack() != connection.seq || packet_header.seq() != connection.ack {
         println!("Packet needs to be resent");
         return Ok(());
     }
@@ -84,9 +67,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // receive Psh packet with data
     let (size, addr) = socket.recv_from(&mut buffer).await?;
-    let packet_header = reliable_udp::packet::Header::parse(&buffer[..packet::HEADER_SIZE])?;
-    let packet_payload = &buffer[packet::HEADER_SIZE..size];
-    if packet_header.ptype != packet::PType::Psh {
+    let packet_header = connection.parse_packet(&buffer[..size])?;
+    let packet_payload = &buffer[packet_header.payload_offset()..size];
+    if packet_header.ptype() != packet::PType::Psh {
         println!("Not a SynAck packet");
         return Ok(());
     }
@@ -96,12 +79,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("Bad checksum");
         return Ok(());
     }
-    if packet_header.ack != connection.seq {
+    if packet_header.ack() != connection.seq {
         println!("Packet needs to be resent");
         return Ok(());
     }
 
-    connection.ack += packet_payload.len() as u32;
+    connection.advance_ack(packet_payload.len())?;
     println!(
         "Received from the client: {:?}",
         str::from_utf8(packet_payload).unwrap()
@@ -109,29 +92,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // send Psh packet with data
     let data = Some(packet_payload);
-    let header_checksum = packet::Header::calculate_header_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::Psh,
-    );
-    let checksum = packet::Header::calculate_checksum(
-        connection.seq,
-        connection.ack,
-        packet::PType::Psh,
-        header_checksum,
-        data,
-    );
-    let packet_header = packet::Header {
-        seq: connection.seq,
-        ack: connection.ack,
-        ptype: packet::PType::Psh,
-        header_checksum,
-        checksum,
-    };
-    let packet = packet::packet_to_binary(packet_header, data);
+    let packet_header =
+        packet::Header::from_connection_state(&connection, packet::PType::Psh, data);
+    let packet = packet::packet_to_binary(packet_header, data)?;
     socket.send_to(&packet, addr).await?;
 
-    connection.seq += unsafe { data.unwrap_unchecked().len() as u32 };
     println!("Message sent");
 
     Ok(())
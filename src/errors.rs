@@ -4,11 +4,27 @@ pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub mod packet_parsing_errors {
     use super::*;
-    use crate::packet::MAX_PACKET_SIZE;
+    use crate::packet::{HEADER_SIZE, MAX_PACKET_SIZE};
 
     #[derive(Debug, Clone, Error)]
-    #[error("Packet should be at least 65 bytes")]
-    pub struct TooSmallPacket;
+    #[error("Packet should be at least {} bytes, got: {}", self.min, self.size)]
+    pub struct TooSmallPacket {
+        pub size: usize,
+        pub min: usize,
+    }
+    impl TooSmallPacket {
+        pub fn new(size: usize) -> TooSmallPacket {
+            TooSmallPacket::with_min(size, HEADER_SIZE)
+        }
+
+        pub fn with_min(size: usize, min: usize) -> TooSmallPacket {
+            TooSmallPacket { size, min }
+        }
+    }
+
+    #[derive(Debug, Clone, Error)]
+    #[error("Packet type is not set")]
+    pub struct MissingPacketType;
 
     #[derive(Debug, Clone, Error)]
     #[error("Unknown packet type: {}", self.ptype)]
@@ -22,13 +38,49 @@ pub mod packet_parsing_errors {
     }
 
     #[derive(Debug, Clone, Error)]
-    #[error("Too big packet: {}, the max is: {}", self.size, MAX_PACKET_SIZE)]
+    #[error("Too big packet: {}, the max is: {}", self.size, self.max)]
     pub struct TooBigPacket {
         pub size: usize,
+        pub max: usize,
     }
     impl TooBigPacket {
         pub fn new(size: usize) -> TooBigPacket {
-            TooBigPacket { size }
+            TooBigPacket::with_max(size, MAX_PACKET_SIZE)
+        }
+
+        pub fn with_max(size: usize, max: usize) -> TooBigPacket {
+            TooBigPacket { size, max }
+        }
+    }
+
+    #[derive(Debug, Clone, Error)]
+    #[error("Malformed header option of kind: {}", self.kind)]
+    pub struct MalformedOption {
+        pub kind: u8,
+    }
+    impl MalformedOption {
+        pub fn new(kind: u8) -> MalformedOption {
+            MalformedOption { kind }
+        }
+    }
+}
+
+pub mod packet_building_errors {
+    use super::*;
+    use crate::packet::MAX_OPTIONS_SIZE;
+
+    #[derive(Debug, Clone, Error)]
+    #[error("Header was modified after its checksums were calculated, seal it again")]
+    pub struct UnsealedHeader;
+
+    #[derive(Debug, Clone, Error)]
+    #[error("Too long header options: {}, the max is: {}", self.size, MAX_OPTIONS_SIZE)]
+    pub struct TooLongOptions {
+        pub size: usize,
+    }
+    impl TooLongOptions {
+        pub fn new(size: usize) -> TooLongOptions {
+            TooLongOptions { size }
         }
     }
 }
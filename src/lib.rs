@@ -1,3 +1,26 @@
 pub mod errors;
 pub mod manager;
 pub mod packet;
+
+/// Crate version
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Version of the wire format. 2 added the options length byte and header options
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Optional cargo features compiled into this build
+pub fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+
+    if cfg!(feature = "bytes") {
+        features.push("bytes");
+    }
+    // the simd module only exists on x86, elsewhere the feature is a no-op
+    if cfg!(all(
+        feature = "simd",
+        any(target_arch = "x86", target_arch = "x86_64")
+    )) {
+        features.push("simd");
+    }
+
+    features
+}
@@ -7,6 +7,11 @@
 // use std::collections::HashMap;
 // use std::sync::{Arc, Mutex};
 
+use crate::errors::*;
+use crate::packet::{
+    Header, DEFAULT_MAX_DATAGRAM_SIZE, HEADER_SIZE, MAX_PACKET_SIZE, MAX_PAYLOAD_SIZE,
+};
+
 #[macro_export]
 macro_rules! box_array {
     ($val:expr ; $len:expr) => {{
@@ -34,10 +39,64 @@ pub struct Connection {
     pub seq: u32,
     pub ack: u32,
 
-    pub previous_seq: u32,
-
     pub is_open: bool,
     pub last_response: u64,
+
+    /// Datagrams above this are dropped by `parse_packet`
+    pub max_datagram_size: usize,
+}
+
+/// A closed connection at seq/ack 0 that accepts datagrams up to `DEFAULT_MAX_DATAGRAM_SIZE`.
+impl Default for Connection {
+    fn default() -> Self {
+        Connection {
+            seq: 0,
+            ack: 0,
+            is_open: false,
+            last_response: 0,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+        }
+    }
+}
+
+impl Connection {
+    /// Largest payload a single packet without options can carry so that a peer with the same
+    /// `max_datagram_size` still accepts it. Zero if the limit is smaller than a header.
+    pub fn max_payload_for_send(&self) -> usize {
+        self.max_datagram_size
+            .min(MAX_PACKET_SIZE)
+            .saturating_sub(HEADER_SIZE)
+    }
+
+    /// Moves seq past `len` sent bytes, wrapping at the u32 boundary.
+    pub fn advance_seq(&mut self, len: usize) -> Result<()> {
+        self.seq = self.seq.wrapping_add(Connection::checked_len(len)?);
+
+        Ok(())
+    }
+
+    /// Moves ack past `len` received bytes, wrapping at the u32 boundary.
+    pub fn advance_ack(&mut self, len: usize) -> Result<()> {
+        self.ack = self.ack.wrapping_add(Connection::checked_len(len)?);
+
+        Ok(())
+    }
+
+    // no single packet can carry more than MAX_PAYLOAD_SIZE
+    fn checked_len(len: usize) -> Result<u32> {
+        if len > MAX_PAYLOAD_SIZE {
+            return Err(
+                packet_parsing_errors::TooBigPacket::with_max(len, MAX_PAYLOAD_SIZE).into(),
+            );
+        }
+
+        Ok(len as u32)
+    }
+
+    /// Parses a datagram received on this connection, enforcing `max_datagram_size`.
+    pub fn parse_packet(&self, data: &[u8]) -> Result<Header> {
+        Header::parse_with_limit(data, self.max_datagram_size)
+    }
 }
 
 // pub struct SocketsManager {
@@ -1,11 +1,64 @@
-#![allow(arithmetic_overflow)]
 use crate::errors::*;
+use crate::manager::Connection;
+use std::ops::{Range, RangeInclusive};
+
+/// Where a header field sits on the wire
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldLayout {
+    pub offset: usize,
+    pub width: usize,
+}
+
+impl FieldLayout {
+    /// A field of `width` bytes starting right where `previous` ends.
+    pub const fn after(previous: FieldLayout, width: usize) -> FieldLayout {
+        FieldLayout {
+            offset: previous.end(),
+            width,
+        }
+    }
+
+    pub const fn end(&self) -> usize {
+        self.offset + self.width
+    }
 
-pub const HEADER_SIZE: usize = 14;
+    pub const fn range(&self) -> Range<usize> {
+        self.offset..self.end()
+    }
+}
+
+pub const SEQ_FIELD: FieldLayout = FieldLayout {
+    offset: 0,
+    width: 4,
+};
+pub const ACK_FIELD: FieldLayout = FieldLayout::after(SEQ_FIELD, 4);
+pub const OPTIONS_LEN_FIELD: FieldLayout = FieldLayout::after(ACK_FIELD, 1);
+pub const PTYPE_FIELD: FieldLayout = FieldLayout::after(OPTIONS_LEN_FIELD, 1);
+pub const HEADER_CHECKSUM_FIELD: FieldLayout = FieldLayout::after(PTYPE_FIELD, 2);
+pub const CHECKSUM_FIELD: FieldLayout = FieldLayout::after(HEADER_CHECKSUM_FIELD, 2);
+
+pub const HEADER_SIZE: usize = CHECKSUM_FIELD.end();
+
+// fields are laid out back to back, so only their widths can go wrong
+const _: () = assert!(
+    SEQ_FIELD.width == size_of::<u32>()
+        && ACK_FIELD.width == size_of::<u32>()
+        && OPTIONS_LEN_FIELD.width == size_of::<u8>()
+        && PTYPE_FIELD.width == size_of::<PType>()
+        && HEADER_CHECKSUM_FIELD.width == size_of::<u16>()
+        && CHECKSUM_FIELD.width == size_of::<u16>()
+        && HEADER_SIZE == 14
+);
 pub const MAX_PACKET_SIZE: usize = 65507;
+pub const MAX_PAYLOAD_SIZE: usize = MAX_PACKET_SIZE - HEADER_SIZE;
+/// Parse limit for connections, the largest UDP payload that fits a 1500 byte IPv4 MTU
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1472;
+/// ptype values left for private/experimental packet types
+pub const EXPERIMENTAL_PTYPES: RangeInclusive<u8> = 240..=255;
+pub const MAX_OPTIONS_SIZE: usize = u8::MAX as usize;
 
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PType {
     Syn = 1,
     SynAck,
@@ -14,39 +67,300 @@ pub enum PType {
     Fin,
 }
 
+/// Optional header fields, sent as kind/length/value between the fixed
+/// header and the payload. Unknown kinds are skipped when parsing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeaderOption {
+    Mss(u16),
+    WindowScale(u8),
+    SackPermitted,
+    Timestamps { value: u32, echo: u32 },
+}
+
+impl HeaderOption {
+    fn encode(&self, to: &mut Vec<u8>) {
+        match *self {
+            HeaderOption::Mss(mss) => {
+                to.extend([1, 2]);
+                to.extend(mss.to_be_bytes());
+            }
+            HeaderOption::WindowScale(shift) => to.extend([2, 1, shift]),
+            HeaderOption::SackPermitted => to.extend([3, 0]),
+            HeaderOption::Timestamps { value, echo } => {
+                to.extend([4, 8]);
+                to.extend(value.to_be_bytes());
+                to.extend(echo.to_be_bytes());
+            }
+        }
+    }
+
+    fn decode(kind: u8, value: &[u8]) -> Result<Option<HeaderOption>> {
+        let option = match (kind, value.len()) {
+            (1, 2) => HeaderOption::Mss(u16::from_be_bytes(value.try_into()?)),
+            (2, 1) => HeaderOption::WindowScale(value[0]),
+            (3, 0) => HeaderOption::SackPermitted,
+            (4, 8) => HeaderOption::Timestamps {
+                value: u32::from_be_bytes(value[0..4].try_into()?),
+                echo: u32::from_be_bytes(value[4..8].try_into()?),
+            },
+            (1..=4, _) => return Err(packet_parsing_errors::MalformedOption::new(kind).into()),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(option))
+    }
+}
+
+/// Walks kind/length/value triples, calling `f` with every known option.
+fn decode_options<F>(mut raw: &[u8], mut f: F) -> Result<()>
+where
+    F: FnMut(HeaderOption),
+{
+    while !raw.is_empty() {
+        if raw.len() < 2 || raw.len() < 2 + raw[1] as usize {
+            return Err(packet_parsing_errors::MalformedOption::new(raw[0]).into());
+        }
+        let (kind, len) = (raw[0], raw[1] as usize);
+
+        if let Some(option) = HeaderOption::decode(kind, &raw[2..2 + len])? {
+            f(option);
+        }
+
+        raw = &raw[2 + len..];
+    }
+
+    Ok(())
+}
+
+fn add_words(checksum: u16, dt: &[u8]) -> u16 {
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    if is_x86_feature_detected!("sse2") {
+        return unsafe { simd::add_words_sse2(checksum, dt) };
+    }
+
+    add_words_scalar(checksum, dt)
+}
+
+// public so tests can compare it against the SIMD path
+#[doc(hidden)]
+pub fn add_words_scalar(mut checksum: u16, dt: &[u8]) -> u16 {
+    if dt.len().is_multiple_of(2) {
+        for index in (0..dt.len()).step_by(2) {
+            checksum = checksum.wrapping_add((dt[index] as u16) << 8);
+            checksum = checksum.wrapping_add(dt[index + 1] as u16);
+        }
+    } else {
+        for index in (0..dt.len() - 1).step_by(2) {
+            checksum = checksum.wrapping_add((dt[index] as u16) << 8);
+            checksum = checksum.wrapping_add(dt[index + 1] as u16);
+        }
+        checksum = checksum.wrapping_add((dt[dt.len() - 1] as u16) << 8);
+    }
+
+    checksum
+}
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+mod simd {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// Same sum as `add_words_scalar`, 16 bytes per iteration.
+    /// The sum of big-endian words is (sum of even bytes << 8) + sum of odd bytes,
+    /// both are summed with `_mm_sad_epu8` into 64-bit lanes.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn add_words_sse2(checksum: u16, dt: &[u8]) -> u16 {
+        let zero = _mm_setzero_si128();
+        let low_bytes = _mm_set1_epi16(0x00ff);
+        let mut evens = zero;
+        let mut odds = zero;
+
+        let chunks = dt.chunks_exact(16);
+        let tail = chunks.remainder();
+        for chunk in chunks {
+            let v = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            evens = _mm_add_epi64(evens, _mm_sad_epu8(_mm_and_si128(v, low_bytes), zero));
+            odds = _mm_add_epi64(odds, _mm_sad_epu8(_mm_srli_epi16(v, 8), zero));
+        }
+
+        let mut lanes = [0u64; 2];
+        _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, evens);
+        let even_sum = lanes[0] + lanes[1];
+        _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, odds);
+        let odd_sum = lanes[0] + lanes[1];
+
+        let checksum = checksum.wrapping_add(((even_sum << 8) + odd_sum) as u16);
+
+        // chunks are 16 bytes, so the tail still starts on a word boundary
+        super::add_words_scalar(checksum, tail)
+    }
+}
+
+/// Payload checksum fed in chunks, gives the same result as
+/// `Header::calculate_checksum` over the whole payload.
+/// For a header with options, feed the raw options before the payload.
+pub struct ChecksumState {
+    checksum: u16,
+    // first byte of a word split between two chunks
+    pending: Option<u8>,
+}
+
+impl ChecksumState {
+    pub fn new(seq: u32, ack: u32, ptype: PType, header_checksum: u16) -> ChecksumState {
+        ChecksumState {
+            checksum: Header::calculate_checksum(seq, ack, ptype, header_checksum, None),
+            pending: None,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        if let Some(high) = self.pending.take() {
+            self.checksum = add_words(self.checksum, &[high, data[0]]);
+            data = &data[1..];
+        }
+
+        let whole_words = data.len() & !1;
+        self.checksum = add_words(self.checksum, &data[..whole_words]);
+
+        if whole_words < data.len() {
+            self.pending = Some(data[whole_words]);
+        }
+    }
+
+    pub fn finalize(self) -> u16 {
+        match self.pending {
+            Some(high) => add_words(self.checksum, &[high]),
+            None => self.checksum,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Header {
-    pub seq: u32,
-    pub ack: u32,
-    // padding 1 byte
-    pub ptype: PType,
-    pub header_checksum: u16,
-    pub checksum: u16,
+    seq: u32,
+    ack: u32,
+    // options length 1 byte
+    ptype: PType,
+    header_checksum: u16,
+    checksum: u16,
+    // raw options as they go on the wire
+    options: Vec<u8>,
+    // false once a field changed after the checksums were calculated
+    sealed: bool,
 }
 
 impl Header {
+    /// Builds a header with both checksums calculated over `data`.
+    pub fn new(seq: u32, ack: u32, ptype: PType, data: Option<&[u8]>) -> Header {
+        let mut header = Header {
+            seq,
+            ack,
+            ptype,
+            header_checksum: 0,
+            checksum: 0,
+            options: Vec::new(),
+            sealed: false,
+        };
+        header.seal(data);
+
+        header
+    }
+
+    /// Builds a sealed header carrying the connection's current seq and ack,
+    /// so every packet also acknowledges what was received so far.
+    pub fn from_connection_state(
+        connection: &Connection,
+        ptype: PType,
+        data: Option<&[u8]>,
+    ) -> Header {
+        Header::new(connection.seq, connection.ack, ptype, data)
+    }
+
+    /// Recalculates both checksums from the current fields and `data`.
+    pub fn seal(&mut self, data: Option<&[u8]>) {
+        self.header_checksum = self.expected_header_checksum();
+        self.checksum = self.expected_checksum(data);
+        self.sealed = true;
+    }
+
+    /// Whether no setter was called since the last `seal` or `parse`. This says nothing about
+    /// the checksums being right: parsed headers are sealed even when corrupt, and the payload
+    /// isn't tracked, so sealing over one payload and sending another goes unnoticed.
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    pub fn set_seq(&mut self, seq: u32) {
+        self.seq = seq;
+        self.sealed = false;
+    }
+
+    pub fn set_ack(&mut self, ack: u32) {
+        self.ack = ack;
+        self.sealed = false;
+    }
+
+    pub fn set_ptype(&mut self, ptype: PType) {
+        self.ptype = ptype;
+        self.sealed = false;
+    }
+
+    pub fn set_options(&mut self, options: &[HeaderOption]) -> Result<()> {
+        let mut raw = Vec::new();
+        for option in options {
+            option.encode(&mut raw);
+        }
+        if raw.len() > MAX_OPTIONS_SIZE {
+            return Err(packet_building_errors::TooLongOptions::new(raw.len()).into());
+        }
+
+        self.options = raw;
+        self.sealed = false;
+
+        Ok(())
+    }
+
     pub fn parse(data: &[u8]) -> Result<Header> {
         if data.len() < HEADER_SIZE {
-            return Err(packet_parsing_errors::TooSmallPacket.into());
+            return Err(packet_parsing_errors::TooSmallPacket::new(data.len()).into());
         } else if data.len() > MAX_PACKET_SIZE {
             return Err(packet_parsing_errors::TooBigPacket::new(data.len()).into());
         }
 
-        let seq: u32 = u32::from_be_bytes(data[0..4].try_into()?);
+        let seq: u32 = u32::from_be_bytes(data[SEQ_FIELD.range()].try_into()?);
 
-        let ack: u32 = u32::from_be_bytes(data[4..8].try_into()?);
+        let ack: u32 = u32::from_be_bytes(data[ACK_FIELD.range()].try_into()?);
 
-        let ptype: PType = match data[9] {
+        let ptype_byte = data[PTYPE_FIELD.offset];
+        let ptype: PType = match ptype_byte {
+            0 => return Err(packet_parsing_errors::MissingPacketType.into()),
             1 => PType::Syn,
             2 => PType::SynAck,
             3 => PType::Ack,
             4 => PType::Psh,
             5 => PType::Fin,
-            _ => return Err(packet_parsing_errors::UknownPType::new(data[8]).into()),
+            _ => return Err(packet_parsing_errors::UknownPType::new(ptype_byte).into()),
         };
 
-        let header_checksum: u16 = u16::from_be_bytes(data[10..12].try_into()?);
+        let header_checksum: u16 =
+            u16::from_be_bytes(data[HEADER_CHECKSUM_FIELD.range()].try_into()?);
 
-        let checksum: u16 = u16::from_be_bytes(data[12..14].try_into()?);
+        let checksum: u16 = u16::from_be_bytes(data[CHECKSUM_FIELD.range()].try_into()?);
+
+        let options_end = HEADER_SIZE + data[OPTIONS_LEN_FIELD.offset] as usize;
+        if data.len() < options_end {
+            return Err(
+                packet_parsing_errors::TooSmallPacket::with_min(data.len(), options_end).into(),
+            );
+        }
+        let options = data[HEADER_SIZE..options_end].to_vec();
+        decode_options(&options, |_| {})?;
 
         Ok(Header {
             seq,
@@ -54,19 +368,137 @@ impl Header {
             ptype,
             header_checksum,
             checksum,
+            options,
+            sealed: true,
         })
     }
 
+    /// Same as `parse`, but rejects datagrams bigger than `max_datagram_size`.
+    /// The limit can't be raised above `MAX_PACKET_SIZE`.
+    pub fn parse_with_limit(data: &[u8], max_datagram_size: usize) -> Result<Header> {
+        let max = max_datagram_size.min(MAX_PACKET_SIZE);
+        if data.len() > max {
+            return Err(packet_parsing_errors::TooBigPacket::with_max(data.len(), max).into());
+        }
+
+        Header::parse(data)
+    }
+
+    /// Same as `parse`, but a packet whose ptype falls in `EXPERIMENTAL_PTYPES`
+    /// is passed to `handler` with its raw ptype and the whole datagram
+    /// instead of being rejected, and `Ok(None)` is returned.
+    pub fn parse_with_experimental<F>(data: &[u8], handler: F) -> Result<Option<Header>>
+    where
+        F: FnOnce(u8, &[u8]),
+    {
+        if (HEADER_SIZE..=MAX_PACKET_SIZE).contains(&data.len())
+            && EXPERIMENTAL_PTYPES.contains(&data[PTYPE_FIELD.offset])
+        {
+            handler(data[PTYPE_FIELD.offset], data);
+            return Ok(None);
+        }
+
+        Header::parse(data).map(Some)
+    }
+
+    /// Parses one packet with a `payload_len` byte payload off the front of
+    /// `input` and advances it past the packet, so buffers holding several
+    /// packets can be parsed in a chain. `input` is untouched on error.
+    pub fn parse_cursor<'a>(
+        input: &mut &'a [u8],
+        payload_len: usize,
+    ) -> Result<(Header, &'a [u8])> {
+        let header = Header::parse(&input[..input.len().min(MAX_PACKET_SIZE)])?;
+
+        let end = header.payload_offset().saturating_add(payload_len);
+        if end > MAX_PACKET_SIZE {
+            return Err(packet_parsing_errors::TooBigPacket::new(end).into());
+        } else if end > input.len() {
+            return Err(packet_parsing_errors::TooSmallPacket::with_min(input.len(), end).into());
+        }
+
+        let payload = &input[header.payload_offset()..end];
+        *input = &input[end..];
+
+        Ok((header, payload))
+    }
+
+    /// Parses the header off the front of `buf` and returns the rest as a
+    /// payload view sharing the same allocation. `buf` is left empty.
+    #[cfg(feature = "bytes")]
+    pub fn parse_bytes(buf: &mut bytes::Bytes) -> Result<(Header, bytes::Bytes)> {
+        let header = Header::parse(buf)?;
+
+        let _ = buf.split_to(header.payload_offset());
+
+        Ok((header, buf.split_to(buf.len())))
+    }
+
+    pub fn seq(&self) -> u32 {
+        self.seq
+    }
+
+    pub fn ack(&self) -> u32 {
+        self.ack
+    }
+
+    pub fn ptype(&self) -> PType {
+        self.ptype
+    }
+
+    /// ptype as it goes on the wire
+    pub fn ptype_byte(&self) -> u8 {
+        self.ptype as u8
+    }
+
+    pub fn header_checksum(&self) -> u16 {
+        self.header_checksum
+    }
+
+    pub fn checksum(&self) -> u16 {
+        self.checksum
+    }
+
+    /// Known options in wire order, unknown ones are left out.
+    pub fn options(&self) -> Vec<HeaderOption> {
+        let mut options = Vec::new();
+        // raw options were validated when they were parsed or encoded
+        let _ = decode_options(&self.options, |option| options.push(option));
+
+        options
+    }
+
+    /// Where the payload starts in the datagram, after the fixed header and options.
+    pub fn payload_offset(&self) -> usize {
+        HEADER_SIZE + self.options.len()
+    }
+
+    // the options length is summed on its own, so a corrupted length can't be
+    // cancelled out by the bytes it moves between the options and the payload
+    fn expected_header_checksum(&self) -> u16 {
+        Header::calculate_header_checksum(self.seq, self.ack, self.ptype)
+            .wrapping_add(self.options.len() as u16)
+    }
+
+    // everything after the fixed header, options first, as one run of words
+    fn expected_checksum(&self, data: Option<&[u8]>) -> u16 {
+        let mut state = ChecksumState::new(self.seq, self.ack, self.ptype, self.header_checksum);
+        state.update(&self.options);
+        state.update(data.unwrap_or_default());
+
+        state.finalize()
+    }
+
     pub fn calculate_header_checksum(seq: u32, ack: u32, ptype: PType) -> u16 {
         let mut checksum: u16 = 0;
 
-        checksum += (seq >> 16) as u16;
-        checksum += seq as u16;
+        checksum = checksum.wrapping_add((seq >> 16) as u16);
+        checksum = checksum.wrapping_add(seq as u16);
 
-        checksum += (ack >> 16) as u16;
-        checksum += ack as u16;
+        checksum = checksum.wrapping_add((ack >> 16) as u16);
+        checksum = checksum.wrapping_add(ack as u16);
 
-        checksum += ptype as u16;
+        checksum = checksum.wrapping_add(ptype as u16);
 
         checksum
     }
@@ -80,57 +512,57 @@ impl Header {
     ) -> u16 {
         let mut checksum: u16 = header_checksum;
 
-        checksum += (seq >> 16) as u16;
-        checksum += seq as u16;
-
-        checksum += (ack >> 16) as u16;
-        checksum += ack as u16;
+        checksum = checksum.wrapping_add((seq >> 16) as u16);
+        checksum = checksum.wrapping_add(seq as u16);
 
-        checksum += ptype as u16;
+        checksum = checksum.wrapping_add((ack >> 16) as u16);
+        checksum = checksum.wrapping_add(ack as u16);
 
-        if data.is_some() {
-            let dt = unsafe { data.unwrap_unchecked() };
+        checksum = checksum.wrapping_add(ptype as u16);
 
-            if dt.len() % 2 == 0 {
-                for index in (0..dt.len()).step_by(2) {
-                    checksum += (dt[index] as u16) << 8;
-                    checksum += dt[index + 1] as u16;
-                }
-            } else {
-                for index in (0..dt.len() - 1).step_by(2) {
-                    checksum += (dt[index] as u16) << 8;
-                    checksum += dt[index + 1] as u16;
-                }
-                checksum += (dt[dt.len() - 1] as u16) << 8;
-            }
+        if let Some(dt) = data {
+            checksum = add_words(checksum, dt);
         }
 
         checksum
     }
 
     pub fn verify_header_checksum(&self) -> bool {
-        let calculated_checksum = Header::calculate_header_checksum(self.seq, self.ack, self.ptype);
-
-        self.header_checksum == calculated_checksum
+        self.header_checksum == self.expected_header_checksum()
     }
 
     pub fn verify_checksum(&self, data: Option<&[u8]>) -> bool {
-        let calculated_checksum =
-            Header::calculate_checksum(self.seq, self.ack, self.ptype, self.header_checksum, data);
-
-        self.checksum == calculated_checksum
+        self.checksum == self.expected_checksum(data)
     }
 }
 
+/// Parses every datagram and checks both of its checksums.
+pub fn verify_batch(packets: &[&[u8]]) -> Vec<bool> {
+    packets
+        .iter()
+        .map(|packet| match Header::parse(packet) {
+            Ok(header) => {
+                header.verify_header_checksum()
+                    && header.verify_checksum(Some(&packet[header.payload_offset()..]))
+            }
+            Err(_) => false,
+        })
+        .collect()
+}
+
 /// needs rewriting
-pub fn packet_to_binary(header: Header, data: Option<&[u8]>) -> Vec<u8> {
-    let mut to_return: Vec<u8>;
-    if data.is_some() {
-        to_return = Vec::with_capacity(HEADER_SIZE + unsafe { data.unwrap_unchecked().len() });
-    } else {
-        to_return = Vec::with_capacity(HEADER_SIZE);
+pub fn packet_to_binary(header: Header, data: Option<&[u8]>) -> Result<Vec<u8>> {
+    if !header.sealed {
+        return Err(packet_building_errors::UnsealedHeader.into());
     }
 
+    let size = header.payload_offset() + data.map_or(0, |dt| dt.len());
+    if size > MAX_PACKET_SIZE {
+        return Err(packet_parsing_errors::TooBigPacket::new(size).into());
+    }
+
+    let mut to_return: Vec<u8> = Vec::with_capacity(size);
+
     for b in header.seq.to_be_bytes() {
         to_return.push(b);
     }
@@ -139,7 +571,7 @@ pub fn packet_to_binary(header: Header, data: Option<&[u8]>) -> Vec<u8> {
         to_return.push(b);
     }
 
-    to_return.push(0);
+    to_return.push(header.options.len() as u8);
 
     to_return.push(header.ptype as u8);
 
@@ -151,11 +583,11 @@ pub fn packet_to_binary(header: Header, data: Option<&[u8]>) -> Vec<u8> {
         to_return.push(b);
     }
 
-    if data.is_some() {
-        for b in unsafe { data.unwrap_unchecked() } {
-            to_return.push(*b);
-        }
+    to_return.extend_from_slice(&header.options);
+
+    if let Some(dt) = data {
+        to_return.extend_from_slice(dt);
     }
 
-    to_return
+    Ok(to_return)
 }
@@ -1,5 +1,7 @@
 extern crate reliable_udp;
-use reliable_udp::packet::PType;
+use reliable_udp::manager;
+use reliable_udp::packet;
+use reliable_udp::packet::{Header, PType};
 
 #[test]
 fn verify_checksum() {
@@ -19,3 +21,673 @@ fn verify_checksum() {
 
     assert_eq!(checksum, 2312u16);
 }
+
+#[test]
+fn raw_field_getters() {
+    let header_checksum = Header::calculate_header_checksum(7, 9, PType::Psh);
+    let checksum = Header::calculate_checksum(7, 9, PType::Psh, header_checksum, None);
+    let header = Header::new(7, 9, PType::Psh, None);
+
+    assert_eq!(header.seq(), 7);
+    assert_eq!(header.ack(), 9);
+    assert_eq!(header.header_checksum(), header_checksum);
+    assert_eq!(header.checksum(), checksum);
+
+    let ptype_byte = header.ptype_byte();
+    let binary = packet::packet_to_binary(header, None).unwrap();
+
+    assert_eq!(binary[9], ptype_byte);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn parse_bytes_shares_payload() {
+    let data = b"payload".as_slice();
+    let header = Header::new(1, 2, PType::Psh, Some(data));
+
+    let original = bytes::Bytes::from(packet::packet_to_binary(header, Some(data)).unwrap());
+    let mut buf = original.clone();
+
+    let (header, payload) = Header::parse_bytes(&mut buf).unwrap();
+
+    assert!(header.verify_header_checksum());
+    assert!(header.verify_checksum(Some(&payload)));
+    assert_eq!(&payload[..], data);
+    assert_eq!(payload.as_ptr(), original[packet::HEADER_SIZE..].as_ptr());
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn experimental_ptypes() {
+    let mut datagram = [0u8; packet::HEADER_SIZE + 3];
+
+    datagram[9] = 250;
+    let mut routed = None;
+    let header = Header::parse_with_experimental(&datagram, |ptype, data| {
+        routed = Some((ptype, data.len()));
+    })
+    .unwrap();
+    assert!(header.is_none());
+    assert_eq!(routed, Some((250, packet::HEADER_SIZE + 3)));
+
+    datagram[9] = 50;
+    let mut routed = false;
+    let result = Header::parse_with_experimental(&datagram, |_, _| routed = true);
+    assert!(result.is_err());
+    assert!(!routed);
+
+    datagram[9] = PType::Ack as u8;
+    let header = Header::parse_with_experimental(&datagram, |_, _| panic!("not experimental"))
+        .unwrap()
+        .unwrap();
+    assert!(header.ptype() == PType::Ack);
+}
+
+#[test]
+fn mutated_header_must_be_resealed() {
+    let mut header = Header::new(1, 2, PType::Ack, None);
+    assert!(header.is_sealed());
+
+    header.set_seq(100);
+    assert!(!header.is_sealed());
+    assert!(!header.verify_header_checksum());
+    assert!(packet::packet_to_binary(header, None).is_err());
+
+    let mut header = Header::new(1, 2, PType::Ack, None);
+    header.set_seq(100);
+    header.seal(None);
+    let binary = packet::packet_to_binary(header, None).unwrap();
+
+    let parsed = Header::parse(&binary).unwrap();
+    assert_eq!(parsed.seq(), 100);
+    assert!(parsed.verify_header_checksum());
+    assert!(parsed.verify_checksum(None));
+}
+
+#[test]
+fn max_payload_for_send() {
+    let connection = manager::Connection::default();
+
+    let max_payload = connection.max_payload_for_send();
+    assert_eq!(
+        max_payload + packet::HEADER_SIZE,
+        packet::DEFAULT_MAX_DATAGRAM_SIZE
+    );
+
+    let data = vec![0u8; max_payload];
+    let header = Header::new(0, 0, PType::Psh, Some(&data));
+    let binary = packet::packet_to_binary(header, Some(&data)).unwrap();
+    assert_eq!(binary.len(), packet::DEFAULT_MAX_DATAGRAM_SIZE);
+    assert!(connection.parse_packet(&binary).is_ok());
+
+    let data = vec![0u8; max_payload + 1];
+    let header = Header::new(0, 0, PType::Psh, Some(&data));
+    let binary = packet::packet_to_binary(header, Some(&data)).unwrap();
+    assert!(connection.parse_packet(&binary).is_err());
+
+    let connection = manager::Connection {
+        max_datagram_size: usize::MAX,
+        ..Default::default()
+    };
+    assert_eq!(connection.max_payload_for_send(), packet::MAX_PAYLOAD_SIZE);
+
+    let connection = manager::Connection {
+        max_datagram_size: packet::HEADER_SIZE - 1,
+        ..Default::default()
+    };
+    assert_eq!(connection.max_payload_for_send(), 0);
+}
+
+#[test]
+fn packet_errors_format_with_core_only() {
+    use core::fmt::Write;
+    use reliable_udp::errors::packet_parsing_errors::*;
+
+    // fixed buffer, so formatting needs nothing beyond core
+    struct StackBuf {
+        buf: [u8; 64],
+        len: usize,
+    }
+    impl Write for StackBuf {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            if end > self.buf.len() {
+                return Err(core::fmt::Error);
+            }
+            self.buf[self.len..end].copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+    fn format(error: &dyn core::error::Error) -> StackBuf {
+        let mut out = StackBuf {
+            buf: [0; 64],
+            len: 0,
+        };
+        write!(out, "{}", error).unwrap();
+        out
+    }
+
+    let out = format(&TooSmallPacket::new(5));
+    assert_eq!(
+        &out.buf[..out.len],
+        b"Packet should be at least 14 bytes, got: 5"
+    );
+
+    let out = format(&UknownPType::new(42));
+    assert_eq!(&out.buf[..out.len], b"Unknown packet type: 42");
+
+    let out = format(&TooBigPacket::new(70000));
+    assert_eq!(
+        &out.buf[..out.len],
+        b"Too big packet: 70000, the max is: 65507"
+    );
+}
+
+#[test]
+fn version_and_features() {
+    assert_eq!(reliable_udp::VERSION, env!("CARGO_PKG_VERSION"));
+    assert_eq!(reliable_udp::PROTOCOL_VERSION, 2);
+
+    let features = reliable_udp::features();
+    assert_eq!(features.contains(&"bytes"), cfg!(feature = "bytes"));
+    assert_eq!(
+        features.contains(&"simd"),
+        cfg!(all(
+            feature = "simd",
+            any(target_arch = "x86", target_arch = "x86_64")
+        ))
+    );
+}
+
+#[test]
+fn oversized_packet_is_rejected() {
+    use reliable_udp::errors::packet_parsing_errors::TooBigPacket;
+
+    let data = vec![0u8; packet::MAX_PAYLOAD_SIZE + 1];
+    let header = Header::new(0, 0, PType::Psh, Some(&data));
+
+    let error = packet::packet_to_binary(header, Some(&data)).unwrap_err();
+    let error = error.downcast_ref::<TooBigPacket>().unwrap();
+    assert_eq!(error.size, packet::MAX_PACKET_SIZE + 1);
+}
+
+#[test]
+fn header_options_round_trip() {
+    use reliable_udp::packet::HeaderOption;
+
+    let data = b"payload".as_slice();
+    let options = [
+        HeaderOption::Mss(1200),
+        HeaderOption::Timestamps { value: 1, echo: 2 },
+    ];
+    let mut header = Header::new(1, 2, PType::Syn, None);
+    header.set_options(&options).unwrap();
+    header.seal(Some(data));
+    let mut binary = packet::packet_to_binary(header, Some(data)).unwrap();
+
+    let parsed = Header::parse(&binary).unwrap();
+    assert_eq!(parsed.options(), options);
+    assert!(parsed.verify_header_checksum());
+    assert!(parsed.verify_checksum(Some(&binary[parsed.payload_offset()..])));
+    assert_eq!(&binary[parsed.payload_offset()..], data);
+
+    // an unknown kind in between is skipped, but still covered by the checksums
+    let options_end = parsed.payload_offset();
+    binary.splice(options_end..options_end, [99, 3, 7, 7, 7]);
+    binary[packet::OPTIONS_LEN_FIELD.offset] += 5;
+
+    let parsed = Header::parse(&binary).unwrap();
+    assert_eq!(parsed.options(), options);
+    assert_eq!(&binary[parsed.payload_offset()..], data);
+    assert!(!parsed.verify_header_checksum());
+    assert!(!parsed.verify_checksum(Some(data)));
+
+    // truncated option value
+    binary[packet::OPTIONS_LEN_FIELD.offset] -= 1;
+    binary.remove(options_end + 4);
+    assert!(Header::parse(&binary).is_err());
+}
+
+#[test]
+fn checksum_golden_vectors() {
+    let (seq, ack, ptype) = (0x12345678, 0x9abcdef0, PType::Psh);
+    let header_checksum = Header::calculate_header_checksum(seq, ack, ptype);
+    assert_eq!(header_checksum, 0xe25c);
+
+    let all_ff = [0xffu8; 64];
+    let vectors: [(&str, &[u8], u16); 7] = [
+        ("empty", &[], 0xc4b8),
+        ("one byte", &[0x2a], 0xeeb8),
+        ("odd length", b"hello", 0x0889),
+        ("even length", b"hello!", 0x08aa),
+        ("all zeros", &[0; 16], 0xc4b8),
+        ("all 0xFF", &all_ff, 0xc498),
+        (
+            "wraps around",
+            &[0xff, 0xfe, 0xff, 0xfe, 0xff, 0xfe, 0xf0],
+            0xb4b2,
+        ),
+    ];
+    for (name, data, expected) in vectors {
+        let checksum = Header::calculate_checksum(seq, ack, ptype, header_checksum, Some(data));
+        assert_eq!(checksum, expected, "{}", name);
+    }
+
+    assert_eq!(
+        Header::calculate_checksum(seq, ack, ptype, header_checksum, None),
+        0xc4b8
+    );
+
+    // header fields alone wrap past u16::MAX
+    let header_checksum = Header::calculate_header_checksum(u32::MAX, u32::MAX, PType::Fin);
+    assert_eq!(header_checksum, 0x0001);
+    assert_eq!(
+        Header::calculate_checksum(u32::MAX, u32::MAX, PType::Fin, header_checksum, None),
+        0x0002
+    );
+}
+
+#[test]
+fn checksum_matches_reference_on_random_payloads() {
+    use rand::rngs::StdRng;
+    use rand::{RngExt, SeedableRng};
+
+    // plain per-word reference for the scalar path. calculate_checksum only takes
+    // the SIMD path when built with the simd feature on x86, CI runs that with --all-features
+    fn reference(mut checksum: u16, data: &[u8]) -> u16 {
+        for word in data.chunks(2) {
+            let high = (word[0] as u16) << 8;
+            let low = word.get(1).copied().unwrap_or(0) as u16;
+            checksum = checksum.wrapping_add(high).wrapping_add(low);
+        }
+        checksum
+    }
+
+    let mut rng = StdRng::seed_from_u64(251);
+    for len in (0..200).chain([1000, 1001, packet::MAX_PAYLOAD_SIZE]) {
+        let data: Vec<u8> = (0..len).map(|_| rng.random()).collect();
+        let seq: u32 = rng.random();
+        let ack: u32 = rng.random();
+
+        let header_checksum = Header::calculate_header_checksum(seq, ack, PType::Psh);
+        let empty = Header::calculate_checksum(seq, ack, PType::Psh, header_checksum, None);
+        let checksum =
+            Header::calculate_checksum(seq, ack, PType::Psh, header_checksum, Some(&data));
+
+        assert_eq!(checksum, reference(empty, &data), "len {}", len);
+        assert_eq!(
+            checksum,
+            packet::add_words_scalar(empty, &data),
+            "len {}",
+            len
+        );
+    }
+}
+
+#[test]
+fn zero_ptype_is_missing_not_unknown() {
+    use reliable_udp::errors::packet_parsing_errors::{MissingPacketType, UknownPType};
+
+    let mut datagram = [0u8; packet::HEADER_SIZE];
+
+    let error = Header::parse(&datagram).err().unwrap();
+    assert!(error.is::<MissingPacketType>());
+
+    datagram[packet::PTYPE_FIELD.offset] = 99;
+    let error = Header::parse(&datagram).err().unwrap();
+    assert_eq!(error.downcast_ref::<UknownPType>().unwrap().ptype, 99);
+}
+
+#[test]
+fn parse_boundary_lengths() {
+    use reliable_udp::errors::packet_parsing_errors::TooSmallPacket;
+
+    let mut binary = packet::packet_to_binary(Header::new(1, 2, PType::Ack, None), None).unwrap();
+    binary.push(0xaa);
+
+    for len in [0, 8, 9, 13] {
+        let error = Header::parse(&binary[..len]).err().unwrap();
+        let error = error.downcast_ref::<TooSmallPacket>().unwrap();
+        assert_eq!((error.size, error.min), (len, packet::HEADER_SIZE));
+    }
+    for len in [14, 15] {
+        let header = Header::parse(&binary[..len]).unwrap();
+        assert!(header.verify_header_checksum(), "len {}", len);
+    }
+
+    // options length pointing past the end of the datagram
+    binary[packet::OPTIONS_LEN_FIELD.offset] = 2;
+    let error = Header::parse(&binary).err().unwrap();
+    let error = error.downcast_ref::<TooSmallPacket>().unwrap();
+    assert_eq!((error.size, error.min), (15, 16));
+}
+
+#[test]
+fn verify_batch_flags_bad_packets() {
+    let data = b"payload".as_slice();
+    let valid =
+        packet::packet_to_binary(Header::new(1, 2, PType::Psh, Some(data)), Some(data)).unwrap();
+    let empty = packet::packet_to_binary(Header::new(3, 4, PType::Ack, None), None).unwrap();
+
+    let mut bad_payload = valid.clone();
+    *bad_payload.last_mut().unwrap() ^= 1;
+    let mut bad_header = valid.clone();
+    bad_header[0] ^= 1;
+
+    let packets = [
+        valid.as_slice(),
+        bad_payload.as_slice(),
+        empty.as_slice(),
+        bad_header.as_slice(),
+        &valid[..5],
+    ];
+    assert_eq!(
+        packet::verify_batch(&packets),
+        [true, false, true, false, false]
+    );
+}
+
+#[test]
+fn single_byte_header_corruption_is_detected() {
+    use rand::rngs::StdRng;
+    use rand::{RngExt, SeedableRng};
+
+    let ptypes = [
+        PType::Syn,
+        PType::SynAck,
+        PType::Ack,
+        PType::Psh,
+        PType::Fin,
+    ];
+    let mut rng = StdRng::seed_from_u64(278);
+
+    for _ in 0..100 {
+        let ptype = ptypes[rng.random_range(0..ptypes.len())];
+        let data: Vec<u8> = (0..rng.random_range(0..=255))
+            .map(|_| rng.random())
+            .collect();
+        let header = Header::new(rng.random(), rng.random(), ptype, Some(&data));
+        let binary = packet::packet_to_binary(header, Some(&data)).unwrap();
+
+        for position in 0..packet::HEADER_SIZE {
+            for flip in 1..=u8::MAX {
+                let mut corrupted = binary.clone();
+                corrupted[position] ^= flip;
+
+                let Ok(parsed) = Header::parse(&corrupted) else {
+                    continue;
+                };
+                let payload = &corrupted[parsed.payload_offset()..];
+
+                // the header checksum can't cover the payload checksum, which covers it
+                if packet::CHECKSUM_FIELD.range().contains(&position) {
+                    assert!(parsed.verify_header_checksum());
+                    assert!(!parsed.verify_checksum(Some(payload)));
+                } else {
+                    assert!(
+                        !parsed.verify_header_checksum(),
+                        "byte {} ^ {:#x} went undetected",
+                        position,
+                        flip
+                    );
+                }
+            }
+        }
+    }
+
+    // moves a whole unknown option out of the payload, its words add up to -4
+    let data = [200, 2, 55, 250, 1, 2, 3];
+    let header = Header::new(1, 2, PType::Psh, Some(&data));
+    let mut binary = packet::packet_to_binary(header, Some(&data)).unwrap();
+    binary[packet::OPTIONS_LEN_FIELD.offset] ^= 4;
+
+    let parsed = Header::parse(&binary).unwrap();
+    assert_eq!(parsed.payload_offset(), packet::HEADER_SIZE + 4);
+    assert!(!parsed.verify_header_checksum());
+}
+
+#[test]
+fn header_from_connection_state_carries_latest_ack() {
+    let mut connection = manager::Connection {
+        seq: 10,
+        ack: 20,
+        is_open: true,
+        last_response: 0,
+        max_datagram_size: packet::DEFAULT_MAX_DATAGRAM_SIZE,
+    };
+
+    // peer data arrives
+    let received = b"hello".as_slice();
+    connection.ack += received.len() as u32;
+
+    let data = b"reply".as_slice();
+    let header = Header::from_connection_state(&connection, PType::Psh, Some(data));
+    assert!(header.is_sealed());
+
+    let binary = packet::packet_to_binary(header, Some(data)).unwrap();
+    let parsed = Header::parse(&binary).unwrap();
+    assert_eq!(parsed.seq(), 10);
+    assert_eq!(parsed.ack(), 25);
+    assert!(parsed.verify_checksum(Some(data)));
+}
+
+#[test]
+fn incremental_checksum_matches_one_shot() {
+    use reliable_udp::packet::ChecksumState;
+
+    let data: Vec<u8> = (0..=255u8).cycle().take(1001).collect();
+    let header_checksum = Header::calculate_header_checksum(5, 6, PType::Psh);
+    let expected = Header::calculate_checksum(5, 6, PType::Psh, header_checksum, Some(&data));
+
+    let mut state = ChecksumState::new(5, 6, PType::Psh, header_checksum);
+    state.update(&data[..333]);
+    state.update(&data[333..334]);
+    state.update(&data[334..]);
+    assert_eq!(state.finalize(), expected);
+
+    for split in [0, 1, 2, 499, 500, 1000, 1001] {
+        let mut state = ChecksumState::new(5, 6, PType::Psh, header_checksum);
+        state.update(&data[..split]);
+        state.update(&[]);
+        state.update(&data[split..]);
+        assert_eq!(state.finalize(), expected, "split at {}", split);
+    }
+}
+
+#[test]
+fn parse_cursor_chains_packets() {
+    use reliable_udp::errors::packet_parsing_errors::TooSmallPacket;
+
+    let data = b"first".as_slice();
+    let mut buffer =
+        packet::packet_to_binary(Header::new(1, 2, PType::Psh, Some(data)), Some(data)).unwrap();
+    buffer.extend(packet::packet_to_binary(Header::new(3, 4, PType::Ack, None), None).unwrap());
+
+    let mut cursor = buffer.as_slice();
+
+    let (header, payload) = Header::parse_cursor(&mut cursor, data.len()).unwrap();
+    assert_eq!(header.seq(), 1);
+    assert_eq!(payload, data);
+    assert!(header.verify_checksum(Some(payload)));
+
+    // claiming more payload than is left doesn't move the cursor
+    let left = cursor.len();
+    let error = Header::parse_cursor(&mut cursor, 1).err().unwrap();
+    let error = error.downcast_ref::<TooSmallPacket>().unwrap();
+    assert_eq!((error.size, error.min), (left, packet::HEADER_SIZE + 1));
+    assert_eq!(cursor.len(), left);
+
+    let (header, payload) = Header::parse_cursor(&mut cursor, 0).unwrap();
+    assert_eq!(header.seq(), 3);
+    assert!(payload.is_empty());
+    assert!(header.verify_checksum(None));
+
+    assert!(cursor.is_empty());
+}
+
+#[test]
+fn connection_parse_rejects_datagrams_over_its_limit() {
+    use reliable_udp::errors::packet_parsing_errors::TooBigPacket;
+
+    let connection = manager::Connection {
+        seq: 0,
+        ack: 0,
+        is_open: true,
+        last_response: 0,
+        max_datagram_size: packet::DEFAULT_MAX_DATAGRAM_SIZE,
+    };
+
+    let data = vec![0u8; packet::DEFAULT_MAX_DATAGRAM_SIZE];
+    let binary =
+        packet::packet_to_binary(Header::new(0, 0, PType::Psh, Some(&data)), Some(&data)).unwrap();
+    assert!(binary.len() > packet::DEFAULT_MAX_DATAGRAM_SIZE);
+    assert!(binary.len() <= packet::MAX_PACKET_SIZE);
+    assert!(Header::parse(&binary).is_ok());
+
+    let error = connection.parse_packet(&binary).err().unwrap();
+    let error = error.downcast_ref::<TooBigPacket>().unwrap();
+    assert_eq!(error.max, packet::DEFAULT_MAX_DATAGRAM_SIZE);
+
+    let fitting = &binary[..packet::DEFAULT_MAX_DATAGRAM_SIZE];
+    assert!(connection.parse_packet(fitting).is_ok());
+
+    // the limit can't be raised past MAX_PACKET_SIZE
+    let too_big = vec![0u8; packet::MAX_PACKET_SIZE + 1];
+    let error = Header::parse_with_limit(&too_big, usize::MAX)
+        .err()
+        .unwrap();
+    assert_eq!(
+        error.downcast_ref::<TooBigPacket>().unwrap().max,
+        packet::MAX_PACKET_SIZE
+    );
+}
+
+#[test]
+fn seq_and_ack_advance_across_wrap() {
+    use reliable_udp::errors::packet_parsing_errors::TooBigPacket;
+
+    let mut connection = manager::Connection {
+        seq: u32::MAX,
+        ack: u32::MAX - 2,
+        is_open: true,
+        last_response: 0,
+        max_datagram_size: packet::DEFAULT_MAX_DATAGRAM_SIZE,
+    };
+
+    connection.advance_seq(1).unwrap();
+    assert_eq!(connection.seq, 0);
+
+    connection.advance_ack(5).unwrap();
+    assert_eq!(connection.ack, 2);
+    connection.advance_ack(packet::MAX_PAYLOAD_SIZE).unwrap();
+    assert_eq!(connection.ack, 2 + packet::MAX_PAYLOAD_SIZE as u32);
+
+    let error = connection
+        .advance_ack(packet::MAX_PAYLOAD_SIZE + 1)
+        .unwrap_err();
+    let error = error.downcast_ref::<TooBigPacket>().unwrap();
+    assert_eq!(error.size, packet::MAX_PAYLOAD_SIZE + 1);
+    assert_eq!(error.max, packet::MAX_PAYLOAD_SIZE);
+
+    assert!(connection.advance_seq(usize::MAX).is_err());
+    assert_eq!(connection.ack, 2 + packet::MAX_PAYLOAD_SIZE as u32);
+    assert_eq!(connection.seq, 0);
+}
+
+#[test]
+fn field_layouts_match_parse() {
+    let mut header = Header::new(0xdeadbeef, 0x01234567, PType::SynAck, None);
+    header
+        .set_options(&[packet::HeaderOption::SackPermitted])
+        .unwrap();
+    header.seal(None);
+    let binary = packet::packet_to_binary(header, None).unwrap();
+    let parsed = Header::parse(&binary).unwrap();
+
+    let field = |layout: packet::FieldLayout| &binary[layout.range()];
+
+    assert_eq!(field(packet::SEQ_FIELD), parsed.seq().to_be_bytes());
+    assert_eq!(field(packet::ACK_FIELD), parsed.ack().to_be_bytes());
+    assert_eq!(field(packet::OPTIONS_LEN_FIELD), [2]);
+    assert_eq!(field(packet::PTYPE_FIELD), [parsed.ptype_byte()]);
+    assert_eq!(
+        field(packet::HEADER_CHECKSUM_FIELD),
+        parsed.header_checksum().to_be_bytes()
+    );
+    assert_eq!(
+        field(packet::CHECKSUM_FIELD),
+        parsed.checksum().to_be_bytes()
+    );
+
+    assert_eq!(packet::CHECKSUM_FIELD.end(), packet::HEADER_SIZE);
+    assert_eq!(packet::PTYPE_FIELD.range(), 9..10);
+
+    let layouts = [
+        packet::SEQ_FIELD,
+        packet::ACK_FIELD,
+        packet::OPTIONS_LEN_FIELD,
+        packet::PTYPE_FIELD,
+        packet::HEADER_CHECKSUM_FIELD,
+        packet::CHECKSUM_FIELD,
+    ];
+    let mut covered = [0u8; packet::HEADER_SIZE];
+    for layout in layouts {
+        for index in layout.range() {
+            covered[index] += 1;
+        }
+    }
+    assert_eq!(covered, [1; packet::HEADER_SIZE]);
+}
+
+#[test]
+fn every_ptype_round_trips() {
+    let ptypes = [
+        PType::Syn,
+        PType::SynAck,
+        PType::Ack,
+        PType::Psh,
+        PType::Fin,
+    ];
+    let payload = b"round trip".as_slice();
+
+    for ptype in ptypes {
+        for data in [None, Some(payload)] {
+            let header = Header::new(0x0badf00d, 0xfeedface, ptype, data);
+            let binary = packet::packet_to_binary(header.clone(), data).unwrap();
+
+            let parsed = Header::parse(&binary).unwrap();
+            let parsed_payload = &binary[parsed.payload_offset()..];
+
+            assert_eq!(parsed, header, "{:?} {:?}", ptype, data);
+            assert_eq!(parsed_payload, data.unwrap_or_default());
+            assert!(parsed.verify_header_checksum(), "{:?}", ptype);
+            assert!(parsed.verify_checksum(data), "{:?}", ptype);
+        }
+    }
+}
+
+#[test]
+fn connection_default() {
+    let connection = manager::Connection::default();
+
+    assert_eq!(connection.seq, 0);
+    assert_eq!(connection.ack, 0);
+    assert!(!connection.is_open);
+    assert_eq!(connection.last_response, 0);
+    assert_eq!(
+        connection.max_datagram_size,
+        packet::DEFAULT_MAX_DATAGRAM_SIZE
+    );
+
+    let connection = manager::Connection {
+        seq: 42,
+        max_datagram_size: 512,
+        ..Default::default()
+    };
+
+    assert_eq!(connection.seq, 42);
+    assert_eq!(connection.ack, 0);
+    assert_eq!(connection.max_datagram_size, 512);
+    assert!(connection.parse_packet(&[0u8; 513]).is_err());
+}